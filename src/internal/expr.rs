@@ -0,0 +1,209 @@
+use internal::table::Row;
+use internal::value::Value;
+
+// ========================================================================= //
+
+/// A comparison operator, for use with `Expr::Compare`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CmpOp {
+    /// Equal to.
+    Eq,
+    /// Not equal to.
+    Ne,
+    /// Less than.
+    Lt,
+    /// Less than or equal to.
+    Le,
+    /// Greater than.
+    Gt,
+    /// Greater than or equal to.
+    Ge,
+}
+
+/// An arithmetic operator, for use with `Expr::Binary`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArithOp {
+    /// Addition.
+    Add,
+    /// Subtraction.
+    Sub,
+    /// Multiplication.
+    Mul,
+    /// Division.
+    Div,
+    /// Modulo.
+    Mod,
+}
+
+/// An expression that can be evaluated against a table row, for use in
+/// filtering and projecting rows without matching on `Value`s by hand.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Expr {
+    /// A constant value.
+    Const(Value),
+    /// The value of the named column.
+    Column(String),
+    /// A comparison between the values of two subexpressions.
+    Compare(CmpOp, Box<Expr>, Box<Expr>),
+    /// The logical conjunction of two subexpressions.
+    And(Box<Expr>, Box<Expr>),
+    /// The logical disjunction of two subexpressions.
+    Or(Box<Expr>, Box<Expr>),
+    /// The logical negation of a subexpression.
+    Not(Box<Expr>),
+    /// An arithmetic operation between two integer-valued subexpressions.
+    Binary(ArithOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluates this expression against the given row.
+    pub fn eval(&self, row: &Row) -> Value {
+        match *self {
+            Expr::Const(ref value) => value.clone(),
+            Expr::Column(ref name) => row.get_or_null(name),
+            Expr::Compare(op, ref lhs, ref rhs) => {
+                Value::from_bool(compare(op, &lhs.eval(row), &rhs.eval(row)))
+            }
+            Expr::And(ref lhs, ref rhs) => {
+                Value::from_bool(lhs.eval(row).to_bool() &&
+                                  rhs.eval(row).to_bool())
+            }
+            Expr::Or(ref lhs, ref rhs) => {
+                Value::from_bool(lhs.eval(row).to_bool() ||
+                                  rhs.eval(row).to_bool())
+            }
+            Expr::Not(ref expr) => Value::from_bool(!expr.eval(row).to_bool()),
+            Expr::Binary(op, ref lhs, ref rhs) => {
+                match (lhs.eval(row), rhs.eval(row)) {
+                    (Value::Int(a), Value::Int(b)) => {
+                        Value::Int(arith(op, a, b))
+                    }
+                    _ => Value::Null,
+                }
+            }
+        }
+    }
+}
+
+/// Compares two values using the given operator.  `Null` only ever compares
+/// equal to `Null`; comparing values of two different types (other than the
+/// above) always returns false, rather than ordering them by their enum
+/// discriminant.
+fn compare(op: CmpOp, lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (&Value::Null, &Value::Null) => op == CmpOp::Eq,
+        (&Value::Null, _) | (_, &Value::Null) => false,
+        (&Value::Int(a), &Value::Int(b)) => compare_ord(op, a, b),
+        (&Value::Str(ref a), &Value::Str(ref b)) => compare_ord(op, a, b),
+        _ => false,
+    }
+}
+
+fn compare_ord<T: PartialOrd>(op: CmpOp, lhs: T, rhs: T) -> bool {
+    match op {
+        CmpOp::Eq => lhs == rhs,
+        CmpOp::Ne => lhs != rhs,
+        CmpOp::Lt => lhs < rhs,
+        CmpOp::Le => lhs <= rhs,
+        CmpOp::Gt => lhs > rhs,
+        CmpOp::Ge => lhs >= rhs,
+    }
+}
+
+fn arith(op: ArithOp, lhs: i32, rhs: i32) -> i32 {
+    match op {
+        ArithOp::Add => lhs.wrapping_add(rhs),
+        ArithOp::Sub => lhs.wrapping_sub(rhs),
+        ArithOp::Mul => lhs.wrapping_mul(rhs),
+        ArithOp::Div => if rhs == 0 { 0 } else { lhs.wrapping_div(rhs) },
+        ArithOp::Mod => if rhs == 0 { 0 } else { lhs.wrapping_rem(rhs) },
+    }
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{arith, compare, ArithOp, CmpOp, Expr};
+    use internal::table::{Row, Table};
+    use internal::value::Value;
+
+    #[test]
+    fn compare_null() {
+        assert!(compare(CmpOp::Eq, &Value::Null, &Value::Null));
+        assert!(!compare(CmpOp::Ne, &Value::Null, &Value::Null));
+        assert!(!compare(CmpOp::Eq, &Value::Null, &Value::Int(0)));
+        assert!(!compare(CmpOp::Ne, &Value::Null, &Value::Int(0)));
+        assert!(!compare(CmpOp::Lt, &Value::Null, &Value::Int(5)));
+        assert!(!compare(CmpOp::Gt, &Value::Int(5), &Value::Null));
+    }
+
+    #[test]
+    fn compare_mixed_types_are_never_ordered() {
+        let int_val = Value::Int(1);
+        let str_val = Value::Str("1".to_string());
+        assert!(!compare(CmpOp::Eq, &int_val, &str_val));
+        assert!(!compare(CmpOp::Ne, &int_val, &str_val));
+        assert!(!compare(CmpOp::Lt, &int_val, &str_val));
+        assert!(!compare(CmpOp::Gt, &int_val, &str_val));
+    }
+
+    #[test]
+    fn compare_same_variant() {
+        assert!(compare(CmpOp::Lt, &Value::Int(1), &Value::Int(2)));
+        assert!(compare(CmpOp::Ge, &Value::Int(2), &Value::Int(2)));
+        assert!(compare(CmpOp::Lt,
+                        &Value::Str("a".to_string()),
+                        &Value::Str("b".to_string())));
+    }
+
+    #[test]
+    fn arith_ops() {
+        assert_eq!(arith(ArithOp::Add, 2, 3), 5);
+        assert_eq!(arith(ArithOp::Sub, 2, 3), -1);
+        assert_eq!(arith(ArithOp::Mul, 2, 3), 6);
+        assert_eq!(arith(ArithOp::Div, 7, 2), 3);
+        assert_eq!(arith(ArithOp::Mod, 7, 2), 1);
+    }
+
+    #[test]
+    fn arith_div_and_mod_by_zero_do_not_panic() {
+        assert_eq!(arith(ArithOp::Div, 7, 0), 0);
+        assert_eq!(arith(ArithOp::Mod, 7, 0), 0);
+    }
+
+    #[test]
+    fn eval_logic_and_compare() {
+        let table = Table::new("T".to_string(), vec![], false);
+        let row = Row::new(&table, vec![]);
+        let expr = Expr::And(
+            Box::new(Expr::Compare(CmpOp::Eq,
+                                    Box::new(Expr::Const(Value::Int(1))),
+                                    Box::new(Expr::Const(Value::Int(1))))),
+            Box::new(Expr::Not(Box::new(Expr::Const(Value::Int(0))))));
+        assert_eq!(expr.eval(&row), Value::Int(1));
+
+        let expr = Expr::Binary(ArithOp::Add,
+                                Box::new(Expr::Const(Value::Int(2))),
+                                Box::new(Expr::Const(Value::Int(3))));
+        assert_eq!(expr.eval(&row), Value::Int(5));
+
+        let expr = Expr::Binary(ArithOp::Add,
+                                Box::new(Expr::Const(Value::Int(2))),
+                                Box::new(Expr::Const(Value::Null)));
+        assert_eq!(expr.eval(&row), Value::Null);
+    }
+
+    #[test]
+    fn eval_unknown_column_is_null_not_a_panic() {
+        let table = Table::new("T".to_string(), vec![], false);
+        let row = Row::new(&table, vec![]);
+        let expr = Expr::Compare(CmpOp::Eq,
+                                 Box::new(Expr::Column("NoSuchColumn"
+                                                            .to_string())),
+                                 Box::new(Expr::Const(Value::Null)));
+        assert_eq!(expr.eval(&row), Value::from_bool(true));
+    }
+}
+
+// ========================================================================= //
@@ -1,7 +1,10 @@
+#[cfg(feature = "serde")]
+use base64;
 use internal::column::Column;
+use internal::expr::Expr;
 use internal::streamname;
 use internal::stringpool::StringPool;
-use internal::value::{Value, ValueRef};
+use internal::value::{BinaryCache, FromValue, StreamRef, Value, ValueRef};
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::ops::Index;
 
@@ -62,11 +65,30 @@ impl Table {
                column_name);
     }
 
+    fn opt_index_for_column_name(&self, column_name: &str) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.name() == column_name)
+    }
+
     /// Parses row data from the given data source and returns an interator
     /// over the rows.
-    pub(crate) fn read_rows<R: Read + Seek>(
-        &self, mut reader: R)
-        -> io::Result<Vec<Vec<ValueRef>>> {
+    ///
+    /// On disk, a `Binary`-category cell holds the same kind of string ref
+    /// as a `Str`-category cell, naming the CFB stream that actually holds
+    /// its bytes; `Table` has no way to read a CFB stream itself (that's
+    /// the package layer's job), so for each such cell this resolves the
+    /// stream name via `string_pool` and calls `read_stream` to fetch its
+    /// bytes, caching them in the returned `BinaryCache` (ready to hand to
+    /// `Rows::new`) instead of fetching the same stream twice.
+    pub(crate) fn read_rows<R, F>(&self, mut reader: R,
+                                  string_pool: &StringPool,
+                                  mut read_stream: F)
+                                  -> io::Result<(Vec<Vec<ValueRef>>,
+                                                 BinaryCache)>
+        where R: Read + Seek,
+              F: FnMut(&str) -> io::Result<Vec<u8>>
+    {
         let data_length = reader.seek(SeekFrom::End(0))?;
         reader.seek(SeekFrom::Start(0))?;
         let row_size = self.columns
@@ -81,34 +103,230 @@ impl Table {
         };
         let mut rows =
             vec![Vec::<ValueRef>::with_capacity(num_columns); num_rows];
+        let mut binaries = BinaryCache::new();
         for column in self.columns.iter() {
             let coltype = column.coltype();
+            let is_binary = coltype.is_binary();
             for row in rows.iter_mut() {
-                row.push(coltype
-                             .read_value(&mut reader, self.long_string_refs)?);
+                let value_ref =
+                    coltype.read_value(&mut reader, self.long_string_refs)?;
+                row.push(if is_binary {
+                              resolve_binary_cell(value_ref,
+                                                   string_pool,
+                                                   &mut binaries,
+                                                   &mut read_stream)?
+                          } else {
+                              value_ref
+                          });
             }
         }
-        Ok(rows)
+        Ok((rows, binaries))
     }
 
-    pub(crate) fn write_rows<W: Write>(&self, mut writer: W,
-                                       rows: Vec<Vec<ValueRef>>)
-                                       -> io::Result<()> {
+    /// Writes row data to the given destination.
+    ///
+    /// The inverse of the binary handling in `read_rows`: for a
+    /// `Binary`-category cell, the bytes cached under its `ValueRef::Binary`
+    /// key are looked up in `binaries` and handed to `write_stream` (so the
+    /// caller can write them into the named CFB stream), and the stream
+    /// name itself is interned via `string_pool` and written to the row
+    /// data the same way a `Str`-category cell would be.
+    pub(crate) fn write_rows<W, F>(&self, mut writer: W,
+                                   rows: Vec<Vec<ValueRef>>,
+                                   string_pool: &mut StringPool,
+                                   binaries: &BinaryCache,
+                                   mut write_stream: F)
+                                   -> io::Result<()>
+        where W: Write,
+              F: FnMut(&str, &[u8]) -> io::Result<()>
+    {
         for (index, column) in self.columns.iter().enumerate() {
             let coltype = column.coltype();
+            let is_binary = coltype.is_binary();
             for row in rows.iter() {
+                let value_ref = row[index].clone();
+                let value_ref = if is_binary {
+                    prepare_binary_cell(value_ref,
+                                        string_pool,
+                                        binaries,
+                                        &mut write_stream)?
+                } else {
+                    value_ref
+                };
                 coltype
-                    .write_value(&mut writer,
-                                 row[index],
-                                 self.long_string_refs)?;
+                    .write_value(&mut writer, value_ref, self.long_string_refs)?;
             }
         }
         Ok(())
     }
+
+    /// Creates a streaming, fallible row iterator over the given reader
+    /// (which must hold this table's own row data, column-major, the same
+    /// layout `read_rows`/`write_rows` use), decoding one row at a time
+    /// instead of materializing the whole table up front like `read_rows`
+    /// does.  This lets callers process multi-megabyte tables with
+    /// constant memory, and stop early if the reader errors.
+    ///
+    /// Note: the code that opens a real `.msi`'s CFB streams and decides
+    /// when to call this instead of `read_rows` lives in the package
+    /// layer, which isn't part of this crate snapshot, so this isn't yet
+    /// wired into a real load path; it is, however, fully usable against
+    /// any `Read + Seek` source with this table's row layout today.
+    pub fn streaming_rows<'a, R: Read + Seek>(
+        &'a self, string_pool: &'a StringPool, binaries: &'a BinaryCache,
+        reader: R)
+        -> io::Result<StreamingRows<'a, R>> {
+        Rows::streaming(string_pool, binaries, self, reader)
+    }
+
+    /// Converts the given rows into a sequence of column-name-keyed
+    /// records, giving a stable, human-readable interchange format (e.g.
+    /// for serializing to JSON with `serde_json::to_string`) without
+    /// exposing the internal `ValueRef`/`StringPool` machinery.
+    #[cfg(feature = "serde")]
+    pub fn rows_to_records(&self, rows: Rows) -> Vec<Record> {
+        rows.map(|row| {
+                     self.columns
+                         .iter()
+                         .enumerate()
+                         .map(|(index, column)| {
+                                  (column.name().to_string(),
+                                   row[index].clone())
+                              })
+                         .collect()
+                 })
+            .collect()
+    }
+
+    /// Builds `ValueRef` rows (as consumed by `write_rows`) from a sequence
+    /// of column-name-keyed records, the inverse of `rows_to_records`.
+    /// Missing columns are treated as `Value::Null`.  For a binary-category
+    /// column, a string value is assumed to be the base64 encoding that
+    /// `Value`'s `Serialize` impl produces for a `Binary` cell, and is
+    /// decoded back into one; returns an error if it isn't valid base64.
+    #[cfg(feature = "serde")]
+    pub fn records_to_rows(&self, records: &[Record],
+                           string_pool: &mut StringPool,
+                           binaries: &mut BinaryCache)
+                           -> io::Result<Vec<Vec<ValueRef>>> {
+        records
+            .iter()
+            .enumerate()
+            .map(|(row_index, record)| {
+                self.columns
+                    .iter()
+                    .map(|column| {
+                        let value = record
+                            .get(column.name())
+                            .cloned()
+                            .unwrap_or(Value::Null);
+                        let value = decode_binary_column(
+                            value,
+                            column.coltype().is_binary())?;
+                        Ok(match value {
+                            Value::Binary(bytes) => {
+                                let stream_name =
+                                    format!("{}.{}.{}",
+                                            self.name,
+                                            column.name(),
+                                            row_index);
+                                ValueRef::create_binary(stream_name,
+                                                         bytes,
+                                                         binaries)
+                            }
+                            other => ValueRef::create(other, string_pool)?,
+                        })
+                    })
+                    .collect::<io::Result<Vec<ValueRef>>>()
+            })
+            .collect()
+    }
+}
+
+/// Resolves a `Binary`-category cell read off disk (as the `ValueRef::Str`
+/// string ref naming its CFB stream, the same shape `read_value` produces
+/// for any ref-style column) into a `ValueRef::Binary`, fetching the
+/// stream's bytes via `read_stream` and caching them in `binaries` the
+/// first time that stream name is seen.  `Null` passes through unchanged;
+/// any other shape would mean the column data is corrupt.
+fn resolve_binary_cell<F>(value_ref: ValueRef, string_pool: &StringPool,
+                          binaries: &mut BinaryCache, read_stream: &mut F)
+                          -> io::Result<ValueRef>
+    where F: FnMut(&str) -> io::Result<Vec<u8>>
+{
+    match value_ref {
+        ValueRef::Str(string_ref) => {
+            let stream_name = string_pool.get(string_ref).to_string();
+            if !binaries.contains_key(&stream_name) {
+                let bytes = read_stream(&stream_name)?;
+                binaries.insert(stream_name.clone(), bytes);
+            }
+            Ok(ValueRef::Binary(StreamRef::new(stream_name)))
+        }
+        other => Ok(other),
+    }
+}
+
+/// The inverse of `resolve_binary_cell`: turns a `ValueRef::Binary` back
+/// into the `ValueRef::Str` stream-ref representation `write_value`
+/// expects, handing its cached bytes to `write_stream` so the caller can
+/// write them into that CFB stream.  Returns an error if the cell's bytes
+/// are missing from `binaries` (which would mean it wasn't created via
+/// `ValueRef::create_binary`).
+fn prepare_binary_cell<F>(value_ref: ValueRef, string_pool: &mut StringPool,
+                          binaries: &BinaryCache, write_stream: &mut F)
+                          -> io::Result<ValueRef>
+    where F: FnMut(&str, &[u8]) -> io::Result<()>
+{
+    match value_ref {
+        ValueRef::Binary(stream_ref) => {
+            let stream_name = stream_ref.stream_name().to_string();
+            let bytes = binaries.get(&stream_name).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("No cached bytes for binary value {:?}",
+                            stream_name))
+            })?;
+            write_stream(&stream_name, bytes)?;
+            Ok(ValueRef::Str(string_pool.incref(stream_name)))
+        }
+        other => Ok(other),
+    }
+}
+
+/// If `is_binary_column`, a string value is assumed to be the base64
+/// encoding `Value`'s `Serialize` impl produces for a `Binary` cell, and is
+/// decoded back into one (any other value is passed through unchanged);
+/// non-binary columns are always passed through unchanged.
+#[cfg(feature = "serde")]
+fn decode_binary_column(value: Value, is_binary_column: bool)
+                         -> io::Result<Value> {
+    if !is_binary_column {
+        return Ok(value);
+    }
+    match value {
+        Value::Str(ref encoded) => {
+            base64::decode(encoded)
+                .map(Value::Binary)
+                .map_err(|err| {
+                             io::Error::new(io::ErrorKind::InvalidData,
+                                            err.to_string())
+                         })
+        }
+        other => Ok(other),
+    }
 }
 
 // ========================================================================= //
 
+/// A table row represented as a mapping from column name to value,
+/// suitable for serializing to JSON (or any other self-describing format)
+/// via `serde`.  See `Table::rows_to_records`/`Table::records_to_rows`.
+#[cfg(feature = "serde")]
+pub type Record = ::std::collections::BTreeMap<String, Value>;
+
+// ========================================================================= //
+
 /// One row from a database table.
 pub struct Row<'a> {
     table: &'a Table,
@@ -125,6 +343,41 @@ impl<'a> Row<'a> {
 
     /// Returns the number of columns in the row.
     pub fn len(&self) -> usize { self.table.columns().len() }
+
+    /// Gets the value of the column at the given index, converting it to a
+    /// `T`.  Returns an error if the value is not convertible to `T`.
+    pub fn get<T: FromValue>(&self, index: usize) -> io::Result<T> {
+        T::from_value(&self.values[index])
+    }
+
+    /// Gets the value of the column with the given name, converting it to a
+    /// `T`.  Returns an error if there is no such column, or if the value
+    /// is not convertible to `T`.
+    pub fn get_named<T: FromValue>(&self, column_name: &str)
+                                    -> io::Result<T> {
+        match self.table.opt_index_for_column_name(column_name) {
+            Some(index) => self.get(index),
+            None => {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Table {:?} has no column named {:?}",
+                            self.table.name(),
+                            column_name)))
+            }
+        }
+    }
+
+    /// Returns the value of the column with the given name, or
+    /// `Value::Null` if there is no such column.  Used by the `expr` query
+    /// engine so that a mistyped or cross-table column name causes an
+    /// expression to evaluate to null (and thus not match) rather than
+    /// panicking.
+    pub(crate) fn get_or_null(&self, column_name: &str) -> Value {
+        match self.table.opt_index_for_column_name(column_name) {
+            Some(index) => self.values[index].clone(),
+            None => Value::Null,
+        }
+    }
 }
 
 impl<'a> Index<usize> for Row<'a> {
@@ -147,22 +400,42 @@ impl<'a, 'b> Index<&'b str> for Row<'a> {
 /// An iterator over the rows in a database table.
 pub struct Rows<'a> {
     string_pool: &'a StringPool,
+    binaries: &'a BinaryCache,
     table: &'a Table,
     rows: Vec<Vec<ValueRef>>,
     next_row_index: usize,
 }
 
 impl<'a> Rows<'a> {
-    pub(crate) fn new(string_pool: &'a StringPool, table: &'a Table,
-                      rows: Vec<Vec<ValueRef>>)
+    pub(crate) fn new(string_pool: &'a StringPool, binaries: &'a BinaryCache,
+                      table: &'a Table, rows: Vec<Vec<ValueRef>>)
                       -> Rows<'a> {
         Rows {
             table: table,
             string_pool: string_pool,
+            binaries: binaries,
             rows: rows,
             next_row_index: 0,
         }
     }
+
+    /// Filters this iterator so that only rows matching the given
+    /// expression are yielded, instead of having to iterate and match
+    /// `Value`s by hand.
+    pub fn filter(self, expr: Expr) -> impl Iterator<Item = Row<'a>> {
+        Iterator::filter(self, move |row| expr.eval(row).to_bool())
+    }
+
+    /// Creates a streaming, fallible row iterator over the given reader,
+    /// which decodes one row at a time with constant memory instead of
+    /// materializing the whole table up front like `Table::read_rows`
+    /// does.
+    pub(crate) fn streaming<R: Read + Seek>(
+        string_pool: &'a StringPool, binaries: &'a BinaryCache,
+        table: &'a Table, reader: R)
+        -> io::Result<StreamingRows<'a, R>> {
+        StreamingRows::new(string_pool, binaries, table, reader)
+    }
 }
 
 impl<'a> Iterator for Rows<'a> {
@@ -172,7 +445,9 @@ impl<'a> Iterator for Rows<'a> {
         if self.next_row_index < self.rows.len() {
             let values: Vec<Value> = self.rows[self.next_row_index]
                 .iter()
-                .map(|value_ref| value_ref.to_value(self.string_pool))
+                .map(|value_ref| {
+                         value_ref.to_value(self.string_pool, self.binaries)
+                     })
                 .collect();
             self.next_row_index += 1;
             Some(Row::new(self.table, values))
@@ -191,3 +466,247 @@ impl<'a> Iterator for Rows<'a> {
 impl<'a> ExactSizeIterator for Rows<'a> {}
 
 // ========================================================================= //
+
+/// A streaming, fallible iterator over the rows of a table.  Unlike `Rows`,
+/// this doesn't materialize every row up front; instead, it decodes each
+/// column of a row on demand by seeking the underlying (column-major)
+/// reader to that column's slot for the current row, which lets callers
+/// process multi-megabyte tables with constant memory and stop early if an
+/// error occurs.
+pub struct StreamingRows<'a, R> {
+    table: &'a Table,
+    string_pool: &'a StringPool,
+    binaries: &'a BinaryCache,
+    reader: R,
+    column_offsets: Vec<u64>,
+    column_widths: Vec<u64>,
+    num_rows: usize,
+    next_row_index: usize,
+}
+
+impl<'a, R: Read + Seek> StreamingRows<'a, R> {
+    fn new(string_pool: &'a StringPool, binaries: &'a BinaryCache,
+           table: &'a Table, mut reader: R)
+           -> io::Result<StreamingRows<'a, R>> {
+        let data_length = reader.seek(SeekFrom::End(0))?;
+        let column_widths: Vec<u64> = table
+            .columns()
+            .iter()
+            .map(|column| column.coltype().width(table.long_string_refs))
+            .collect();
+        let row_size = column_widths.iter().sum::<u64>();
+        let num_rows = if row_size > 0 {
+            (data_length / row_size) as usize
+        } else {
+            0
+        };
+        let column_offsets = column_offsets(&column_widths, num_rows);
+        Ok(StreamingRows {
+            table: table,
+            string_pool: string_pool,
+            binaries: binaries,
+            reader: reader,
+            column_offsets: column_offsets,
+            column_widths: column_widths,
+            num_rows: num_rows,
+            next_row_index: 0,
+        })
+    }
+
+    /// Returns the number of rows that have not yet been read.
+    pub fn len(&self) -> usize { self.num_rows - self.next_row_index }
+
+    /// Reads and returns the next row, or `Ok(None)` if there are no rows
+    /// left.  Each column's value is decoded on demand by seeking to
+    /// `column_offset + row_index * column_width`, rather than having
+    /// pre-loaded the whole table, so an I/O error partway through a large
+    /// table can be reported (and the iteration aborted) without having
+    /// paid the cost of reading the rest of it.  For a `Binary`-category
+    /// column, the stream-ref read off disk is resolved against `binaries`
+    /// the same way `ValueRef::to_value` always has: since this iterator
+    /// can't fetch a missing stream's bytes mid-iteration, the caller must
+    /// have pre-populated `binaries` for every such column before
+    /// streaming (`ValueRef::to_value`'s panic-on-miss contract still
+    /// applies).
+    pub fn next(&mut self) -> io::Result<Option<Row<'a>>> {
+        if self.next_row_index >= self.num_rows {
+            return Ok(None);
+        }
+        let row_index = self.next_row_index as u64;
+        let mut value_refs = Vec::with_capacity(self.table.columns().len());
+        for (col_index, column) in self.table.columns().iter().enumerate() {
+            seek_to_cell(&mut self.reader,
+                         &self.column_offsets,
+                         &self.column_widths,
+                         col_index,
+                         row_index)?;
+            let value_ref = column
+                .coltype()
+                .read_value(&mut self.reader, self.table.long_string_refs)?;
+            value_refs.push(if column.coltype().is_binary() {
+                                 stream_ref_for_binary_cell(value_ref,
+                                                            self.string_pool)
+                             } else {
+                                 value_ref
+                             });
+        }
+        let values: Vec<Value> = value_refs
+            .iter()
+            .map(|value_ref| {
+                     value_ref.to_value(self.string_pool, self.binaries)
+                 })
+            .collect();
+        self.next_row_index += 1;
+        Ok(Some(Row::new(self.table, values)))
+    }
+}
+
+/// Seeks `reader` to the slot for column `col_index`'s value in row
+/// `row_index`, within a column-major table stream laid out according to
+/// `column_offsets`/`column_widths` (see `column_offsets`).
+fn seek_to_cell<R: Seek>(reader: &mut R, column_offsets: &[u64],
+                        column_widths: &[u64], col_index: usize,
+                        row_index: u64)
+                        -> io::Result<()> {
+    let offset = column_offsets[col_index] +
+                 row_index * column_widths[col_index];
+    reader.seek(SeekFrom::Start(offset))?;
+    Ok(())
+}
+
+/// Resolves a `Binary`-category cell read off disk (a `ValueRef::Str`
+/// string ref naming its CFB stream) into the `ValueRef::Binary` shape
+/// `to_value` expects; see `resolve_binary_cell` in `Table::read_rows` for
+/// the variant of this that can also fetch not-yet-cached bytes.  `Null`
+/// passes through unchanged.
+fn stream_ref_for_binary_cell(value_ref: ValueRef, string_pool: &StringPool)
+                              -> ValueRef {
+    match value_ref {
+        ValueRef::Str(string_ref) => {
+            ValueRef::Binary(StreamRef::new(string_pool
+                                                 .get(string_ref)
+                                                 .to_string()))
+        }
+        other => other,
+    }
+}
+
+/// Computes, for each column, the offset (within a column-major table
+/// stream) at which that column's block of `num_rows` values begins.
+fn column_offsets(column_widths: &[u64], num_rows: usize) -> Vec<u64> {
+    let mut offsets = Vec::with_capacity(column_widths.len());
+    let mut offset = 0u64;
+    for &width in column_widths.iter() {
+        offsets.push(offset);
+        offset += width * (num_rows as u64);
+    }
+    offsets
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use super::{column_offsets, seek_to_cell, stream_ref_for_binary_cell,
+                BinaryCache, Rows, Table};
+    use internal::stringpool::StringPool;
+    use internal::codepage::CodePage;
+    use internal::value::ValueRef;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn column_offsets_are_column_major() {
+        assert_eq!(column_offsets(&[2, 4, 1], 3), vec![0, 6, 18]);
+        assert_eq!(column_offsets(&[], 5), Vec::<u64>::new());
+        assert_eq!(column_offsets(&[3], 0), vec![0]);
+    }
+
+    #[test]
+    fn seek_to_cell_reaches_the_right_byte_offset() {
+        // Column-major layout for 2 rows with widths [4, 2]:
+        // col0 row0, col0 row1, col1 row0, col1 row1.
+        let column_widths = vec![4u64, 2u64];
+        let offsets = column_offsets(&column_widths, 2);
+        let data = vec![0xAA, 0xAA, 0xAA, 0xAA, 0xBB, 0xBB, 0xBB, 0xBB, 0xCC,
+                        0xCC, 0xDD, 0xDD];
+        let mut reader = Cursor::new(data);
+
+        seek_to_cell(&mut reader, &offsets, &column_widths, 0, 1).unwrap();
+        let mut buf = [0u8; 4];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0xBB, 0xBB, 0xBB, 0xBB]);
+
+        seek_to_cell(&mut reader, &offsets, &column_widths, 1, 0).unwrap();
+        let mut buf = [0u8; 2];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0xCC, 0xCC]);
+
+        seek_to_cell(&mut reader, &offsets, &column_widths, 1, 1).unwrap();
+        let mut buf = [0u8; 2];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0xDD, 0xDD]);
+    }
+
+    #[test]
+    fn stream_ref_for_binary_cell_resolves_the_stream_name() {
+        let mut string_pool = StringPool::new(CodePage::default());
+        let string_ref = string_pool.incref("Binary.Foo".to_string());
+
+        let resolved = stream_ref_for_binary_cell(ValueRef::Str(string_ref),
+                                                   &string_pool);
+        match resolved {
+            ValueRef::Binary(stream_ref) => {
+                assert_eq!(stream_ref.stream_name(), "Binary.Foo");
+            }
+            other => panic!("expected ValueRef::Binary, got {:?}", other),
+        }
+
+        assert_eq!(stream_ref_for_binary_cell(ValueRef::Null, &string_pool),
+                   ValueRef::Null);
+    }
+
+    #[test]
+    fn streaming_rows_over_empty_table() {
+        let table = Table::new("Empty".to_string(), vec![], false);
+        let string_pool = StringPool::new(CodePage::default());
+        let binaries = BinaryCache::new();
+        let mut rows = Rows::streaming(&string_pool,
+                                        &binaries,
+                                        &table,
+                                        Cursor::new(Vec::new()))
+            .unwrap();
+        assert_eq!(rows.len(), 0);
+        assert!(rows.next().unwrap().is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn decode_binary_column_roundtrips_through_json() {
+        extern crate serde_json;
+        use internal::value::Value;
+        use super::decode_binary_column;
+
+        let original = vec![9, 8, 7, 100];
+        let json = serde_json::to_string(&Value::Binary(original.clone()))
+            .unwrap();
+        let decoded: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, Value::Str(::base64::encode(&original)));
+
+        let value = decode_binary_column(decoded, true).unwrap();
+        assert_eq!(value, Value::Binary(original));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn decode_binary_column_ignores_non_binary_columns() {
+        use internal::value::Value;
+        use super::decode_binary_column;
+
+        let value = decode_binary_column(Value::Str("hello".to_string()),
+                                          false)
+            .unwrap();
+        assert_eq!(value, Value::Str("hello".to_string()));
+    }
+}
+
+// ========================================================================= //
@@ -1,7 +1,17 @@
 use internal::stringpool::{StringPool, StringRef};
+#[cfg(feature = "serde")]
+use base64;
+#[cfg(feature = "serde")]
+use serde::de::{self, Visitor};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::ascii::AsciiExt;
+use std::collections::HashMap;
 use std::convert::From;
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
 use std::fmt;
+use std::io;
 use uuid::Uuid;
 
 // ========================================================================= //
@@ -15,6 +25,9 @@ pub enum Value {
     Int(i32),
     /// A string value.
     Str(String),
+    /// A binary (blob) value, such as the contents of a `Binary` or `Icon`
+    /// table cell.
+    Binary(Vec<u8>),
 }
 
 impl Value {
@@ -40,6 +53,7 @@ impl Value {
             Value::Null => None,
             Value::Int(number) => Some(number),
             Value::Str(_) => None,
+            Value::Binary(_) => None,
         }
     }
 
@@ -57,6 +71,25 @@ impl Value {
             Value::Null => None,
             Value::Int(_) => None,
             Value::Str(ref string) => Some(string.as_str()),
+            Value::Binary(_) => None,
+        }
+    }
+
+    /// Returns true if this is a binary value.
+    pub fn is_binary(&self) -> bool {
+        match *self {
+            Value::Binary(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Extracts the binary data if this is a binary value.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Null => None,
+            Value::Int(_) => None,
+            Value::Str(_) => None,
+            Value::Binary(ref bytes) => Some(bytes.as_slice()),
         }
     }
 
@@ -76,6 +109,7 @@ impl Value {
             Value::Null => false,
             Value::Int(number) => number != 0,
             Value::Str(ref string) => !string.is_empty(),
+            Value::Binary(ref bytes) => !bytes.is_empty(),
         }
     }
 }
@@ -86,10 +120,92 @@ impl fmt::Display for Value {
             Value::Null => "NULL".fmt(formatter),
             Value::Int(number) => number.fmt(formatter),
             Value::Str(ref string) => format!("{:?}", string).fmt(formatter),
+            Value::Binary(ref bytes) => {
+                format!("<{} bytes>", bytes.len()).fmt(formatter)
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(bytes: Vec<u8>) -> Value { Value::Binary(bytes) }
+}
+
+/// Serializes as `null`, a number, a string, or (for `Binary`) a
+/// base64-encoded string, giving a stable, human-readable interchange
+/// format for dumping and re-creating table contents.
+#[cfg(feature = "serde")]
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S)
+                                 -> Result<S::Ok, S::Error> {
+        match *self {
+            Value::Null => serializer.serialize_none(),
+            Value::Int(number) => serializer.serialize_i32(number),
+            Value::Str(ref string) => serializer.serialize_str(string),
+            Value::Binary(ref bytes) => {
+                serializer.serialize_str(&base64::encode(bytes))
+            }
         }
     }
 }
 
+/// Deserializes from `null`, a number, or a string.  Binary columns are
+/// re-encoded as `Value::Binary` by the table-level helpers in
+/// `internal::table`, which know (from the column's category) which
+/// strings are actually base64-encoded blobs.
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+                                          -> Result<Value, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = Value;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter)
+                         -> fmt::Result {
+                formatter.write_str("null, an integer, or a string")
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+                Ok(Value::Null)
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64)
+                                        -> Result<Value, E> {
+                i32::try_from(value).map(Value::Int).map_err(|_| {
+                    E::custom(format!("integer {} out of range for i32",
+                                       value))
+                })
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64)
+                                        -> Result<Value, E> {
+                i32::try_from(value).map(Value::Int).map_err(|_| {
+                    E::custom(format!("integer {} out of range for i32",
+                                       value))
+                })
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str)
+                                        -> Result<Value, E> {
+                Ok(Value::Str(value.to_string()))
+            }
+
+            fn visit_string<E: de::Error>(self, value: String)
+                                           -> Result<Value, E> {
+                Ok(Value::Str(value))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
 impl From<i16> for Value {
     fn from(integer: i16) -> Value { Value::Int(integer as i32) }
 }
@@ -122,8 +238,113 @@ impl From<Uuid> for Value {
 
 // ========================================================================= //
 
+fn invalid_conversion(value: &Value, type_name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                    format!("Cannot convert {} to {}", value, type_name))
+}
+
+/// A trait for types that can be extracted from a `Value`, analogous to
+/// `rusqlite`'s `FromSql` trait.  This is used by `Row::get()` and
+/// `Row::get_named()` to decode cell values without panicking.
+pub trait FromValue: Sized {
+    /// Attempts to convert the given value into this type, returning an
+    /// error if the value is of the wrong type.
+    fn from_value(value: &Value) -> io::Result<Self>;
+}
+
+impl FromValue for i32 {
+    fn from_value(value: &Value) -> io::Result<i32> {
+        match *value {
+            Value::Int(number) => Ok(number),
+            _ => Err(invalid_conversion(value, "i32")),
+        }
+    }
+}
+
+impl FromValue for i16 {
+    fn from_value(value: &Value) -> io::Result<i16> {
+        match *value {
+            Value::Int(number) if number >= i16::min_value() as i32 &&
+                                   number <= i16::max_value() as i32 => {
+                Ok(number as i16)
+            }
+            _ => Err(invalid_conversion(value, "i16")),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> io::Result<bool> {
+        match *value {
+            Value::Int(0) => Ok(false),
+            Value::Int(1) => Ok(true),
+            _ => Err(invalid_conversion(value, "bool")),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> io::Result<String> {
+        match *value {
+            Value::Str(ref string) => Ok(string.clone()),
+            _ => Err(invalid_conversion(value, "String")),
+        }
+    }
+}
+
+impl FromValue for Uuid {
+    fn from_value(value: &Value) -> io::Result<Uuid> {
+        match *value {
+            Value::Str(ref string) => {
+                let trimmed = string.trim_matches(|c| c == '{' || c == '}');
+                Uuid::parse_str(trimmed)
+                    .map_err(|_| invalid_conversion(value, "Uuid"))
+            }
+            _ => Err(invalid_conversion(value, "Uuid")),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value) -> io::Result<Option<T>> {
+        match *value {
+            Value::Null => Ok(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+}
+
+// ========================================================================= //
+
+/// An opaque key identifying the in-memory entry in a `BinaryCache` that
+/// holds the bytes for a `Binary`-category cell.  It names the CFB stream
+/// that actually holds those bytes in the `.msi` file, but doesn't read or
+/// write that stream itself: `Table` has no CFB I/O of its own, so
+/// `Table::read_rows`/`write_rows` (see `internal::table`) take a
+/// caller-supplied callback to fetch or store a named stream's bytes, and
+/// use `StreamRef`/`BinaryCache` purely as the in-memory handoff between
+/// that callback and a cell's `ValueRef::Binary`, the same way
+/// `ValueRef::Str` defers through a `StringPool`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamRef(String);
+
+impl StreamRef {
+    /// Creates a reference keyed by the given name.
+    pub(crate) fn new(stream_name: String) -> StreamRef { StreamRef(stream_name) }
+
+    /// Returns the cache key of the referenced entry.
+    pub(crate) fn stream_name(&self) -> &str { &self.0 }
+}
+
+/// An in-memory cache of binary cell bytes, keyed by the name chosen for
+/// them (see `StreamRef`).  This is not backed by the `.msi` file's CFB
+/// streams; it exists so that `ValueRef::Binary` can defer cloning a blob
+/// until the cell is dereferenced, the same way `ValueRef::Str` defers
+/// through a `StringPool`.
+pub(crate) type BinaryCache = HashMap<String, Vec<u8>>;
+
 /// An indirect value from one cell in a database table row.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ValueRef {
     /// A null value.
     Null,
@@ -131,35 +352,79 @@ pub enum ValueRef {
     Int(i32),
     /// A string value.
     Str(StringRef),
+    /// A reference to the stream holding a binary value.
+    Binary(StreamRef),
 }
 
 impl ValueRef {
     /// Interns the given value into the string pool (if it is a string), and
-    /// returns a corresponding `ValueRef`.
-    pub fn create(value: Value, string_pool: &mut StringPool) -> ValueRef {
+    /// returns a corresponding `ValueRef`.  There is no `string_pool`-only
+    /// way to create a `ValueRef::Binary` (its cache entry has to be
+    /// created alongside it); use `ValueRef::create_binary` for that, or
+    /// this will return an error.
+    pub fn create(value: Value, string_pool: &mut StringPool)
+                   -> io::Result<ValueRef> {
         match value {
-            Value::Null => ValueRef::Null,
-            Value::Int(number) => ValueRef::Int(number),
-            Value::Str(string) => ValueRef::Str(string_pool.incref(string)),
+            Value::Null => Ok(ValueRef::Null),
+            Value::Int(number) => Ok(ValueRef::Int(number)),
+            Value::Str(string) => {
+                Ok(ValueRef::Str(string_pool.incref(string)))
+            }
+            Value::Binary(_) => {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Binary values must be cached via \
+                     ValueRef::create_binary instead of ValueRef::create"))
+            }
         }
     }
 
+    /// Creates a `ValueRef` that refers to the given binary value's bytes
+    /// under the given cache key, and inserts those bytes into `binaries`
+    /// so that `to_value` can later resolve them.
+    pub(crate) fn create_binary(stream_name: String, bytes: Vec<u8>,
+                                 binaries: &mut BinaryCache)
+                                 -> ValueRef {
+        binaries.insert(stream_name.clone(), bytes);
+        ValueRef::Binary(StreamRef::new(stream_name))
+    }
+
     /// Removes the reference from the string pool (if is a string reference).
     pub fn remove(self, string_pool: &mut StringPool) {
         match self {
-            ValueRef::Null | ValueRef::Int(_) => {}
+            ValueRef::Null | ValueRef::Int(_) | ValueRef::Binary(_) => {}
             ValueRef::Str(string_ref) => string_pool.decref(string_ref),
         }
     }
 
-    /// Dereferences the `ValueRef` into a `Value`.
-    pub fn to_value(&self, string_pool: &StringPool) -> Value {
+    /// Dereferences the `ValueRef` into a `Value`, looking the referenced
+    /// bytes up in `binaries` for a `Binary` cell.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is a `ValueRef::Binary` whose cache key isn't present
+    /// in `binaries`.  That indicates a `ValueRef::Binary` was constructed
+    /// some way other than `ValueRef::create_binary` (which always inserts
+    /// its entry at the same time), rather than a value worth silently
+    /// coercing to an empty blob.
+    pub fn to_value(&self, string_pool: &StringPool,
+                     binaries: &BinaryCache)
+                     -> Value {
         match *self {
             ValueRef::Null => Value::Null,
             ValueRef::Int(number) => Value::Int(number),
             ValueRef::Str(string_ref) => {
                 Value::Str(string_pool.get(string_ref).to_string())
             }
+            ValueRef::Binary(ref stream_ref) => {
+                match binaries.get(stream_ref.stream_name()) {
+                    Some(bytes) => Value::Binary(bytes.clone()),
+                    None => {
+                        panic!("No cached bytes for binary value {:?}",
+                               stream_ref.stream_name())
+                    }
+                }
+            }
         }
     }
 }
@@ -168,7 +433,7 @@ impl ValueRef {
 
 #[cfg(test)]
 mod tests {
-    use super::{Value, ValueRef};
+    use super::{BinaryCache, FromValue, Value, ValueRef};
     use internal::codepage::CodePage;
     use internal::stringpool::StringPool;
     use uuid::Uuid;
@@ -180,6 +445,8 @@ mod tests {
         assert_eq!(format!("{}", Value::Int(-137)), "-137".to_string());
         assert_eq!(format!("{}", Value::Str("Hello, world!".to_string())),
                    "\"Hello, world!\"".to_string());
+        assert_eq!(format!("{}", Value::Binary(vec![1, 2, 3])),
+                   "<3 bytes>".to_string());
 
         assert_eq!(format!("{:>6}", Value::Null), "  NULL".to_string());
         assert_eq!(format!("[{:<4}]", Value::Int(42)), "[42  ]".to_string());
@@ -198,23 +465,115 @@ mod tests {
             Value::from(Uuid::parse_str(
                 "34ab5c53-9b30-4e14-aef0-2c1c7ba826c0").unwrap()),
             Value::Str("{34AB5C53-9B30-4E14-AEF0-2C1C7BA826C0}".to_string()));
+        assert_eq!(Value::from(vec![1u8, 2, 3]),
+                   Value::Binary(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn value_as_bytes() {
+        assert_eq!(Value::Binary(vec![1, 2, 3]).as_bytes(), Some(&[1, 2, 3][..]));
+        assert_eq!(Value::Int(1).as_bytes(), None);
+        assert!(Value::Binary(vec![]).is_binary());
     }
 
     #[test]
     fn create_value_ref() {
         let mut string_pool = StringPool::new(CodePage::default());
+        let binaries = BinaryCache::new();
 
         let value = Value::Null;
-        let value_ref = ValueRef::create(value.clone(), &mut string_pool);
-        assert_eq!(value_ref.to_value(&string_pool), value);
+        let value_ref =
+            ValueRef::create(value.clone(), &mut string_pool).unwrap();
+        assert_eq!(value_ref.to_value(&string_pool, &binaries), value);
 
         let value = Value::Int(1234567);
-        let value_ref = ValueRef::create(value.clone(), &mut string_pool);
-        assert_eq!(value_ref.to_value(&string_pool), value);
+        let value_ref =
+            ValueRef::create(value.clone(), &mut string_pool).unwrap();
+        assert_eq!(value_ref.to_value(&string_pool, &binaries), value);
 
         let value = Value::Str("Hello, world!".to_string());
-        let value_ref = ValueRef::create(value.clone(), &mut string_pool);
-        assert_eq!(value_ref.to_value(&string_pool), value);
+        let value_ref =
+            ValueRef::create(value.clone(), &mut string_pool).unwrap();
+        assert_eq!(value_ref.to_value(&string_pool, &binaries), value);
+    }
+
+    #[test]
+    fn create_value_ref_rejects_binary() {
+        let mut string_pool = StringPool::new(CodePage::default());
+        let value = Value::Binary(vec![1, 2, 3]);
+        assert!(ValueRef::create(value, &mut string_pool).is_err());
+    }
+
+    #[test]
+    fn create_binary_value_ref() {
+        let string_pool = StringPool::new(CodePage::default());
+        let mut binaries = BinaryCache::new();
+
+        let bytes = vec![9, 8, 7, 6];
+        let value_ref = ValueRef::create_binary("Binary.Foo".to_string(),
+                                                 bytes.clone(),
+                                                 &mut binaries);
+        assert_eq!(value_ref.to_value(&string_pool, &binaries),
+                   Value::Binary(bytes));
+    }
+
+    #[test]
+    fn from_value_success() {
+        assert_eq!(i32::from_value(&Value::Int(42)).unwrap(), 42);
+        assert_eq!(i16::from_value(&Value::Int(-7)).unwrap(), -7i16);
+        assert_eq!(bool::from_value(&Value::Int(1)).unwrap(), true);
+        assert_eq!(bool::from_value(&Value::Int(0)).unwrap(), false);
+        assert_eq!(String::from_value(&Value::Str("foo".to_string()))
+                       .unwrap(),
+                   "foo".to_string());
+        assert_eq!(Uuid::from_value(&Value::Str(
+            "{34AB5C53-9B30-4E14-AEF0-2C1C7BA826C0}".to_string())).unwrap(),
+                   Uuid::parse_str("34ab5c53-9b30-4e14-aef0-2c1c7ba826c0")
+                       .unwrap());
+        assert_eq!(Option::<i32>::from_value(&Value::Null).unwrap(), None);
+        assert_eq!(Option::<i32>::from_value(&Value::Int(3)).unwrap(),
+                   Some(3));
+    }
+
+    #[test]
+    fn from_value_type_mismatch() {
+        assert!(i32::from_value(&Value::Str("nope".to_string())).is_err());
+        assert!(String::from_value(&Value::Int(1)).is_err());
+        assert!(bool::from_value(&Value::Int(2)).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        extern crate serde_json;
+
+        assert_eq!(serde_json::to_string(&Value::Null).unwrap(), "null");
+        assert_eq!(serde_json::to_string(&Value::Int(42)).unwrap(), "42");
+        assert_eq!(serde_json::to_string(&Value::Str("hi".to_string()))
+                       .unwrap(),
+                   "\"hi\"");
+        assert_eq!(serde_json::to_string(&Value::Binary(vec![1, 2, 3]))
+                       .unwrap(),
+                   format!("{:?}", base64::encode(&[1, 2, 3])));
+
+        assert_eq!(serde_json::from_str::<Value>("null").unwrap(),
+                   Value::Null);
+        assert_eq!(serde_json::from_str::<Value>("42").unwrap(),
+                   Value::Int(42));
+        assert_eq!(serde_json::from_str::<Value>("\"hi\"").unwrap(),
+                   Value::Str("hi".to_string()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialize_out_of_range_integer_is_an_error() {
+        extern crate serde_json;
+
+        assert!(serde_json::from_str::<Value>("99999999999999").is_err());
+        assert!(serde_json::from_str::<Value>("-99999999999999").is_err());
+        assert!(serde_json::from_str::<Value>(
+                    &(i64::from(i32::max_value()) + 1).to_string())
+                    .is_err());
     }
 }
 